@@ -20,6 +20,7 @@
 //!
 
 use std::iter;
+use std::str;
 use std::io::Cursor;
 use std::sync::mpsc::Sender;
 
@@ -29,25 +30,53 @@ use network::address::Address;
 use network::message_network;
 use network::message_blockdata;
 use network::encodable::{ConsensusDecodable, ConsensusEncodable};
-use network::encodable::CheckedData;
+use network::encodable::{CheckedData, VarInt};
 use network::serialize::{serialize, RawDecoder, SimpleEncoder, SimpleDecoder};
+use util::hash::{Sha256dHash, murmur3_32, single_sha256, siphash24};
 use util::{self, propagate_err};
 
 /// Serializer for command string
 #[derive(PartialEq, Eq, Clone, Debug)]
-pub struct CommandString(pub String);
+pub struct CommandString(String);
+
+impl CommandString {
+    /// Construct a `CommandString`, checking that it fits the 12-byte
+    /// NUL-padded wire format: at most 12 ASCII bytes, none of them NUL.
+    /// Apart from decoding one off the wire (which applies the same
+    /// checks), this is the only way to build a `CommandString` from outside
+    /// this module, so callers cannot construct one that fails to round-trip
+    /// through `consensus_encode`.
+    pub fn try_from(s: &str) -> Result<CommandString, String> {
+        if s.len() > 12 {
+            return Err(format!("command `{}` is longer than 12 bytes", s));
+        }
+        if !s.bytes().all(|b| b != 0 && b < 0x80) {
+            return Err(format!("command `{}` must be 7-bit ASCII with no NUL bytes", s));
+        }
+        Ok(CommandString(s.to_owned()))
+    }
+
+    /// The command as a string, e.g. `"version"`
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
 
 impl<S: SimpleEncoder> ConsensusEncodable<S> for CommandString {
     #[inline]
     fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
-        use std::intrinsics::copy_nonoverlapping;
-        use std::mem;
-
         let &CommandString(ref inner_str) = self;
-        let mut rawbytes = [0u8; 12]; 
-        unsafe { copy_nonoverlapping(inner_str.as_bytes().as_ptr(),
-                                     rawbytes.as_mut_ptr(),
-                                     mem::size_of::<[u8; 12]>()); }
+        if inner_str.len() > 12 {
+            return Err(s.error(format!("commandstring `{}` is longer than 12 bytes", inner_str)));
+        }
+        if !inner_str.bytes().all(|b| b != 0 && b < 0x80) {
+            return Err(s.error(format!("commandstring `{}` must be 7-bit ASCII with no NUL bytes", inner_str)));
+        }
+
+        let mut rawbytes = [0u8; 12];
+        for (dst, src) in rawbytes.iter_mut().zip(inner_str.as_bytes().iter()) {
+            *dst = *src;
+        }
         rawbytes.consensus_encode(s)
     }
 }
@@ -55,9 +84,18 @@ impl<S: SimpleEncoder> ConsensusEncodable<S> for CommandString {
 impl<D: SimpleDecoder> ConsensusDecodable<D> for CommandString {
     #[inline]
     fn consensus_decode(d: &mut D) -> Result<CommandString, D::Error> {
-        let rawbytes: [u8; 12] = try!(ConsensusDecodable::consensus_decode(d)); 
-        let rv = iter::FromIterator::from_iter(rawbytes.iter().filter_map(|&u| if u > 0 { Some(u as char) } else { None }));
-        Ok(CommandString(rv))
+        let rawbytes: [u8; 12] = try!(ConsensusDecodable::consensus_decode(d));
+        let nul_pos = rawbytes.iter().position(|&b| b == 0).unwrap_or(12);
+        if rawbytes[nul_pos..].iter().any(|&b| b != 0) {
+            return Err(d.error("non-zero byte found after NUL terminator in command string".to_owned()));
+        }
+        if rawbytes[..nul_pos].iter().any(|&b| b >= 0x80) {
+            return Err(d.error("command string is not valid ASCII".to_owned()));
+        }
+        match str::from_utf8(&rawbytes[..nul_pos]) {
+            Ok(cmd) => Ok(CommandString(cmd.to_owned())),
+            Err(_) => Err(d.error("command string is not valid ASCII".to_owned()))
+        }
     }
 }
 
@@ -77,6 +115,571 @@ pub enum SocketResponse {
     ConnectionFailed(util::Error, Sender<()>)
 }
 
+/// BIP37 caps a bloom filter at this many bytes
+const MAX_BLOOM_FILTER_BYTES: usize = 36000;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// A BIP37 bloom filter, as carried by the `filterload` message. Also doubles
+/// as the filter implementation itself: peers maintaining a filter for a
+/// connection can `insert` the data elements they want to match and `contains`
+/// to test incoming transaction data against it.
+pub struct BloomFilter {
+    /// The filter itself
+    pub filter: Vec<u8>,
+    /// The number of hash functions to use
+    pub n_hash_funcs: u32,
+    /// Tweak to the hash function(s) used, for privacy
+    pub n_tweak: u32,
+    /// Flags controlling how matched outputs are updated (see BIP37)
+    pub n_flags: u8
+}
+
+impl BloomFilter {
+    /// Returns the bit indices that `data` hashes to under this filter's
+    /// parameters, or `None` if the filter has no bits to hash into
+    fn hash_indices(&self, data: &[u8]) -> Option<Vec<usize>> {
+        let n_bits = self.filter.len() * 8;
+        if n_bits == 0 {
+            return None;
+        }
+        Some((0..self.n_hash_funcs).map(|i| {
+            let seed = i.wrapping_mul(0xFBA4C795).wrapping_add(self.n_tweak);
+            (murmur3_32(data, seed) as usize) % n_bits
+        }).collect())
+    }
+
+    /// Add a data element to the filter. A no-op on an empty filter.
+    pub fn insert(&mut self, data: &[u8]) {
+        if let Some(indices) = self.hash_indices(data) {
+            for idx in indices {
+                self.filter[idx >> 3] |= 1 << (idx & 7);
+            }
+        }
+    }
+
+    /// Test whether a data element may be in the filter. False positives are
+    /// possible; false negatives are not. An empty filter contains nothing.
+    pub fn contains(&self, data: &[u8]) -> bool {
+        match self.hash_indices(data) {
+            Some(indices) => indices.iter().all(|&idx| self.filter[idx >> 3] & (1 << (idx & 7)) != 0),
+            None => false
+        }
+    }
+}
+
+impl<S: SimpleEncoder> ConsensusEncodable<S> for BloomFilter {
+    fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
+        try!(self.filter.consensus_encode(s));
+        try!(self.n_hash_funcs.consensus_encode(s));
+        try!(self.n_tweak.consensus_encode(s));
+        self.n_flags.consensus_encode(s)
+    }
+}
+
+impl<D: SimpleDecoder> ConsensusDecodable<D> for BloomFilter {
+    fn consensus_decode(d: &mut D) -> Result<BloomFilter, D::Error> {
+        let filter: Vec<u8> = try!(ConsensusDecodable::consensus_decode(d));
+        if filter.is_empty() || filter.len() > MAX_BLOOM_FILTER_BYTES {
+            return Err(d.error(format!("invalid bloom filter length {} (must be 1..={})", filter.len(), MAX_BLOOM_FILTER_BYTES)));
+        }
+        Ok(BloomFilter {
+            filter: filter,
+            n_hash_funcs: try!(ConsensusDecodable::consensus_decode(d)),
+            n_tweak: try!(ConsensusDecodable::consensus_decode(d)),
+            n_flags: try!(ConsensusDecodable::consensus_decode(d))
+        })
+    }
+}
+
+/// The width, in nodes, of a partial Merkle tree at a given height (0 = leaves)
+fn calc_tree_width(height: usize, total_transactions: u32) -> u32 {
+    (total_transactions + (1 << height) - 1) >> height
+}
+
+/// Depth-first reconstruction of a partial Merkle tree, mirroring Bitcoin
+/// Core's `TraverseAndExtract`. Returns the hash of the subtree rooted at
+/// (height, pos), or `None` if the flag/hash data runs out early.
+fn traverse_and_extract(height: usize,
+                         pos: u32,
+                         total_transactions: u32,
+                         hashes: &[Sha256dHash],
+                         flags: &[u8],
+                         bits_used: &mut usize,
+                         hashes_used: &mut usize,
+                         matches: &mut Vec<Sha256dHash>) -> Option<Sha256dHash> {
+    if *bits_used >= flags.len() * 8 {
+        return None;
+    }
+    let bit = (flags[*bits_used >> 3] >> (*bits_used & 7)) & 1 == 1;
+    *bits_used += 1;
+
+    if height == 0 || !bit {
+        if *hashes_used >= hashes.len() {
+            return None;
+        }
+        let hash = hashes[*hashes_used];
+        *hashes_used += 1;
+        if height == 0 && bit {
+            matches.push(hash);
+        }
+        Some(hash)
+    } else {
+        let left = match traverse_and_extract(height - 1, pos * 2, total_transactions, hashes, flags, bits_used, hashes_used, matches) {
+            Some(h) => h,
+            None => return None
+        };
+        let right = if pos * 2 + 1 < calc_tree_width(height - 1, total_transactions) {
+            match traverse_and_extract(height - 1, pos * 2 + 1, total_transactions, hashes, flags, bits_used, hashes_used, matches) {
+                Some(h) => h,
+                None => return None
+            }
+        } else {
+            left
+        };
+        let mut concat = Vec::with_capacity(64);
+        concat.extend_from_slice(&left.data()[..]);
+        concat.extend_from_slice(&right.data()[..]);
+        Some(Sha256dHash::from_data(&concat))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// A `merkleblock` message: a block header together with a partial Merkle
+/// tree proving which transactions (those that matched a peer's bloom
+/// filter) are included in the block, without sending the whole block.
+pub struct MerkleBlock {
+    /// The block header
+    pub header: block::BlockHeader,
+    /// Number of transactions in the block
+    pub total_transactions: u32,
+    /// Merkle tree hashes, in depth-first traversal order
+    pub hashes: Vec<Sha256dHash>,
+    /// Flag bits, packed per byte, least significant bit first
+    pub flags: Vec<u8>
+}
+
+impl MerkleBlock {
+    /// Reconstruct the partial Merkle tree and return the matched txids, or
+    /// `None` if the tree is malformed or its root does not match the
+    /// block header's Merkle root.
+    pub fn extract_matches(&self) -> Option<Vec<Sha256dHash>> {
+        let mut height = 0;
+        while calc_tree_width(height, self.total_transactions) > 1 {
+            height += 1;
+        }
+
+        let mut bits_used = 0;
+        let mut hashes_used = 0;
+        let mut matches = Vec::new();
+        let root = match traverse_and_extract(height, 0, self.total_transactions, &self.hashes, &self.flags,
+                                               &mut bits_used, &mut hashes_used, &mut matches) {
+            Some(root) => root,
+            None => return None
+        };
+        if root != self.header.merkle_root {
+            return None;
+        }
+        // Bitcoin Core rejects a partial Merkle tree that doesn't consume
+        // every hash and every non-padding flag bit -- leftover data means a
+        // peer could append arbitrary extra entries and still have the walk
+        // succeed.
+        if hashes_used != self.hashes.len() {
+            return None;
+        }
+        let total_bits = self.flags.len() * 8;
+        if (bits_used..total_bits).any(|i| (self.flags[i >> 3] >> (i & 7)) & 1 != 0) {
+            return None;
+        }
+        Some(matches)
+    }
+}
+
+impl<S: SimpleEncoder> ConsensusEncodable<S> for MerkleBlock {
+    fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
+        try!(self.header.consensus_encode(s));
+        try!(self.total_transactions.consensus_encode(s));
+        try!(self.hashes.consensus_encode(s));
+        self.flags.consensus_encode(s)
+    }
+}
+
+impl<D: SimpleDecoder> ConsensusDecodable<D> for MerkleBlock {
+    fn consensus_decode(d: &mut D) -> Result<MerkleBlock, D::Error> {
+        Ok(MerkleBlock {
+            header: try!(ConsensusDecodable::consensus_decode(d)),
+            total_transactions: try!(ConsensusDecodable::consensus_decode(d)),
+            hashes: try!(ConsensusDecodable::consensus_decode(d)),
+            flags: try!(ConsensusDecodable::consensus_decode(d))
+        })
+    }
+}
+
+/// The keyed SipHash state used to compute BIP152 short transaction ids for
+/// a single compact block
+struct ShortIdKeys {
+    k0: u64,
+    k1: u64
+}
+
+impl ShortIdKeys {
+    /// Derive the short-id keys from a compact block's header and nonce
+    fn new(header: &block::BlockHeader, nonce: u64) -> Result<ShortIdKeys, util::Error> {
+        let mut preimage = try!(serialize(header));
+        preimage.extend_from_slice(&try!(serialize(&nonce)));
+        let digest = single_sha256(&preimage);
+        let k0 = (0..8).fold(0u64, |acc, i| acc | (digest[i] as u64) << (8 * i));
+        let k1 = (0..8).fold(0u64, |acc, i| acc | (digest[8 + i] as u64) << (8 * i));
+        Ok(ShortIdKeys { k0: k0, k1: k1 })
+    }
+
+    /// Compute the 48-bit (zero-extended to 64 bits) short id for a wtxid
+    fn short_id(&self, wtxid: &Sha256dHash) -> u64 {
+        siphash24(self.k0, self.k1, &wtxid.data()[..]) & 0x0000ffffffffffff
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// A block header and the short ids/prefilled transactions used to
+/// reconstruct a full block with minimal bandwidth (BIP152)
+pub struct HeaderAndShortIds {
+    /// The header of the block being relayed
+    pub header: block::BlockHeader,
+    /// A nonce for use in short transaction id calculations
+    pub nonce: u64,
+    /// The short transaction ids, in the order the transactions appear in
+    /// the block, excluding the `prefilled` ones
+    pub short_ids: Vec<u64>,
+    /// Transactions the sender includes in full (always includes the
+    /// coinbase), as (index in block, transaction) pairs
+    pub prefilled: Vec<(u64, transaction::Transaction)>
+}
+
+impl HeaderAndShortIds {
+    /// Compute the short id for `tx`'s wtxid under this message's keys
+    pub fn short_id_for(&self, tx: &transaction::Transaction) -> Result<u64, util::Error> {
+        Ok(try!(ShortIdKeys::new(&self.header, self.nonce)).short_id(&tx.bitcoin_hash()))
+    }
+
+    /// Given the set of transactions we already know about (e.g. from our
+    /// mempool), return the reconstructed block's transactions in order
+    /// where known, together with the positions we are still missing and
+    /// must request via `getblocktxn`.
+    pub fn reconstruct(&self, known: &[transaction::Transaction]) -> Result<(Vec<Option<transaction::Transaction>>, Vec<u64>), util::Error> {
+        let keys = try!(ShortIdKeys::new(&self.header, self.nonce));
+        let total = self.prefilled.len() + self.short_ids.len();
+        let mut out: Vec<Option<transaction::Transaction>> = iter::repeat(None).take(total).collect();
+        for &(idx, ref tx) in self.prefilled.iter() {
+            out[idx as usize] = Some(tx.clone());
+        }
+
+        let mut short_id_positions = Vec::with_capacity(self.short_ids.len());
+        for i in 0..total {
+            if out[i].is_none() {
+                short_id_positions.push(i as u64);
+            }
+        }
+
+        let mut missing = Vec::new();
+        for (slot, &short_id) in short_id_positions.iter().zip(self.short_ids.iter()) {
+            match known.iter().find(|tx| keys.short_id(&tx.bitcoin_hash()) == short_id) {
+                Some(tx) => out[*slot as usize] = Some(tx.clone()),
+                None => missing.push(*slot)
+            }
+        }
+        Ok((out, missing))
+    }
+}
+
+/// Pack a short id into the 6 little-endian bytes it occupies on the wire
+/// (not the 8 bytes a bare `u64` would use)
+fn short_id_to_bytes(id: u64) -> [u8; 6] {
+    let mut bytes = [0u8; 6];
+    for i in 0..6 {
+        bytes[i] = (id >> (8 * i)) as u8;
+    }
+    bytes
+}
+
+/// Inverse of `short_id_to_bytes`
+fn short_id_from_bytes(bytes: &[u8]) -> u64 {
+    let mut id = 0u64;
+    for i in 0..6 {
+        id |= (bytes[i] as u64) << (8 * i);
+    }
+    id
+}
+
+/// Write a BIP152 short id list: a CompactSize count followed by each id as
+/// exactly 6 little-endian bytes
+fn encode_short_ids<S: SimpleEncoder>(short_ids: &[u64], s: &mut S) -> Result<(), S::Error> {
+    try!(VarInt(short_ids.len() as u64).consensus_encode(s));
+    for &id in short_ids {
+        for &byte in short_id_to_bytes(id).iter() {
+            try!(byte.consensus_encode(s));
+        }
+    }
+    Ok(())
+}
+
+/// Inverse of `encode_short_ids`
+fn decode_short_ids<D: SimpleDecoder>(d: &mut D) -> Result<Vec<u64>, D::Error> {
+    let VarInt(len) = try!(ConsensusDecodable::consensus_decode(d));
+    let mut out = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        let mut bytes = [0u8; 6];
+        for i in 0..6 {
+            bytes[i] = try!(ConsensusDecodable::consensus_decode(d));
+        }
+        out.push(short_id_from_bytes(&bytes));
+    }
+    Ok(out)
+}
+
+/// Write the prefilled transactions: a CompactSize count followed by, for
+/// each one, its differentially-encoded (BIP152) CompactSize index and then
+/// the transaction itself
+fn encode_prefilled<S: SimpleEncoder>(prefilled: &[(u64, transaction::Transaction)], s: &mut S) -> Result<(), S::Error> {
+    let indexes: Vec<u64> = prefilled.iter().map(|&(idx, _)| idx).collect();
+    let diffs = encode_differential_indexes(&indexes);
+    try!(VarInt(prefilled.len() as u64).consensus_encode(s));
+    for (&diff, &(_, ref tx)) in diffs.iter().zip(prefilled.iter()) {
+        try!(VarInt(diff).consensus_encode(s));
+        try!(tx.consensus_encode(s));
+    }
+    Ok(())
+}
+
+/// Inverse of `encode_prefilled`
+fn decode_prefilled<D: SimpleDecoder>(d: &mut D) -> Result<Vec<(u64, transaction::Transaction)>, D::Error> {
+    let VarInt(len) = try!(ConsensusDecodable::consensus_decode(d));
+    let mut diffs = Vec::with_capacity(len as usize);
+    let mut txs = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        let VarInt(diff) = try!(ConsensusDecodable::consensus_decode(d));
+        diffs.push(diff);
+        txs.push(try!(ConsensusDecodable::consensus_decode(d)));
+    }
+    Ok(decode_differential_indexes(&diffs).into_iter().zip(txs.into_iter()).collect())
+}
+
+impl<S: SimpleEncoder> ConsensusEncodable<S> for HeaderAndShortIds {
+    fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
+        try!(self.header.consensus_encode(s));
+        try!(self.nonce.consensus_encode(s));
+        try!(encode_short_ids(&self.short_ids, s));
+        encode_prefilled(&self.prefilled, s)
+    }
+}
+
+impl<D: SimpleDecoder> ConsensusDecodable<D> for HeaderAndShortIds {
+    fn consensus_decode(d: &mut D) -> Result<HeaderAndShortIds, D::Error> {
+        Ok(HeaderAndShortIds {
+            header: try!(ConsensusDecodable::consensus_decode(d)),
+            nonce: try!(ConsensusDecodable::consensus_decode(d)),
+            short_ids: try!(decode_short_ids(d)),
+            prefilled: try!(decode_prefilled(d))
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// `sendcmpct`: negotiates BIP152 compact block relay with a peer
+pub struct SendCmpct {
+    /// Whether the sender wants to be a high-bandwidth peer for this
+    /// connection (sent `cmpctblock` proactively rather than `inv`)
+    pub high_bandwidth: bool,
+    /// The compact block relay protocol version supported
+    pub version: u64
+}
+
+impl<S: SimpleEncoder> ConsensusEncodable<S> for SendCmpct {
+    fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
+        try!((self.high_bandwidth as u8).consensus_encode(s));
+        self.version.consensus_encode(s)
+    }
+}
+
+impl<D: SimpleDecoder> ConsensusDecodable<D> for SendCmpct {
+    fn consensus_decode(d: &mut D) -> Result<SendCmpct, D::Error> {
+        let high_bandwidth: u8 = try!(ConsensusDecodable::consensus_decode(d));
+        Ok(SendCmpct {
+            high_bandwidth: high_bandwidth != 0,
+            version: try!(ConsensusDecodable::consensus_decode(d))
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// `getblocktxn`: requests specific transactions (by index) from a
+/// previously announced compact block
+pub struct GetBlockTxn {
+    /// The hash of the block whose transactions are being requested
+    pub block_hash: Sha256dHash,
+    /// The indexes, within the block, of the transactions being requested
+    pub indexes: Vec<u64>
+}
+
+/// Differentially encode ascending indexes as BIP152 specifies: each stored
+/// value is the gap to the previous absolute index, minus one
+fn encode_differential_indexes(indexes: &[u64]) -> Vec<u64> {
+    let mut out = Vec::with_capacity(indexes.len());
+    let mut last: i64 = -1;
+    for &idx in indexes {
+        out.push((idx as i64 - last - 1) as u64);
+        last = idx as i64;
+    }
+    out
+}
+
+/// Inverse of `encode_differential_indexes`
+fn decode_differential_indexes(encoded: &[u64]) -> Vec<u64> {
+    let mut out = Vec::with_capacity(encoded.len());
+    let mut last: i64 = -1;
+    for &gap in encoded {
+        let idx = last + 1 + gap as i64;
+        out.push(idx as u64);
+        last = idx;
+    }
+    out
+}
+
+impl<S: SimpleEncoder> ConsensusEncodable<S> for GetBlockTxn {
+    fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
+        try!(self.block_hash.consensus_encode(s));
+        encode_differential_indexes(&self.indexes).consensus_encode(s)
+    }
+}
+
+impl<D: SimpleDecoder> ConsensusDecodable<D> for GetBlockTxn {
+    fn consensus_decode(d: &mut D) -> Result<GetBlockTxn, D::Error> {
+        let block_hash = try!(ConsensusDecodable::consensus_decode(d));
+        let encoded: Vec<u64> = try!(ConsensusDecodable::consensus_decode(d));
+        Ok(GetBlockTxn { block_hash: block_hash, indexes: decode_differential_indexes(&encoded) })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// `blocktxn`: the transactions requested via `getblocktxn`
+pub struct BlockTxn {
+    /// The hash of the block these transactions belong to
+    pub block_hash: Sha256dHash,
+    /// The requested transactions, in the order they were requested
+    pub transactions: Vec<transaction::Transaction>
+}
+
+impl<S: SimpleEncoder> ConsensusEncodable<S> for BlockTxn {
+    fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
+        try!(self.block_hash.consensus_encode(s));
+        self.transactions.consensus_encode(s)
+    }
+}
+
+impl<D: SimpleDecoder> ConsensusDecodable<D> for BlockTxn {
+    fn consensus_decode(d: &mut D) -> Result<BlockTxn, D::Error> {
+        Ok(BlockTxn {
+            block_hash: try!(ConsensusDecodable::consensus_decode(d)),
+            transactions: try!(ConsensusDecodable::consensus_decode(d))
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Copy)]
+/// The reason a peer gives for rejecting a message, as a one-byte code (BIP61)
+pub enum RejectReason {
+    /// The message was malformed
+    Malformed,
+    /// The message was invalid
+    Invalid,
+    /// The message was obsolete or not relevant to current consensus rules
+    Obsolete,
+    /// The message was a duplicate of one already processed
+    Duplicate,
+    /// The message violated non-consensus policy rules
+    Nonstandard,
+    /// The transaction paid too low a fee to relay (dust)
+    Dust,
+    /// The transaction did not have enough fee
+    InsufficientFee,
+    /// The message conflicted with a checkpoint
+    Checkpoint
+}
+
+impl<S: SimpleEncoder> ConsensusEncodable<S> for RejectReason {
+    fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
+        let code: u8 = match *self {
+            RejectReason::Malformed => 0x01,
+            RejectReason::Invalid => 0x10,
+            RejectReason::Obsolete => 0x11,
+            RejectReason::Duplicate => 0x12,
+            RejectReason::Nonstandard => 0x40,
+            RejectReason::Dust => 0x41,
+            RejectReason::InsufficientFee => 0x42,
+            RejectReason::Checkpoint => 0x43
+        };
+        code.consensus_encode(s)
+    }
+}
+
+impl<D: SimpleDecoder> ConsensusDecodable<D> for RejectReason {
+    fn consensus_decode(d: &mut D) -> Result<RejectReason, D::Error> {
+        let code: u8 = try!(ConsensusDecodable::consensus_decode(d));
+        match code {
+            0x01 => Ok(RejectReason::Malformed),
+            0x10 => Ok(RejectReason::Invalid),
+            0x11 => Ok(RejectReason::Obsolete),
+            0x12 => Ok(RejectReason::Duplicate),
+            0x40 => Ok(RejectReason::Nonstandard),
+            0x41 => Ok(RejectReason::Dust),
+            0x42 => Ok(RejectReason::InsufficientFee),
+            0x43 => Ok(RejectReason::Checkpoint),
+            _ => Err(d.error(format!("unknown reject code `{:#x}`", code)))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// A `reject` message (BIP61), sent by a peer to explain why it dropped
+/// something we sent it.
+pub struct RejectMessage {
+    /// The type of message rejected
+    pub message: CommandString,
+    /// The reason code for the rejection
+    pub ccode: RejectReason,
+    /// A human-readable explanation of the rejection
+    pub reason: String,
+    /// The hash of the rejected object, present only when `message` is
+    /// `tx` or `block`
+    pub hash: Option<Sha256dHash>
+}
+
+impl<S: SimpleEncoder> ConsensusEncodable<S> for RejectMessage {
+    fn consensus_encode(&self, s: &mut S) -> Result<(), S::Error> {
+        try!(self.message.consensus_encode(s));
+        try!(self.ccode.consensus_encode(s));
+        try!(self.reason.consensus_encode(s));
+        if let Some(ref hash) = self.hash {
+            try!(hash.consensus_encode(s));
+        }
+        Ok(())
+    }
+}
+
+impl<D: SimpleDecoder> ConsensusDecodable<D> for RejectMessage {
+    fn consensus_decode(d: &mut D) -> Result<RejectMessage, D::Error> {
+        let message: CommandString = try!(ConsensusDecodable::consensus_decode(d));
+        let ccode: RejectReason = try!(ConsensusDecodable::consensus_decode(d));
+        let reason: String = try!(ConsensusDecodable::consensus_decode(d));
+        // `hash` is only sent when rejecting a `tx` or `block`, and some
+        // peers omit it even then -- read it best-effort instead of failing
+        // the whole message on a short buffer.
+        let hash = match &message.0[..] {
+            "tx" | "block" => ConsensusDecodable::consensus_decode(d).ok(),
+            _ => None
+        };
+        Ok(RejectMessage { message: message, ccode: ccode, reason: reason, hash: hash })
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 /// A Network message payload. Proper documentation is available on the Bitcoin
 /// wiki https://en.bitcoin.it/wiki/Protocol_specification
@@ -103,37 +706,82 @@ pub enum NetworkMessage {
     Block(block::Block),
     /// `headers`
     Headers(Vec<block::LoneBlockHeader>),
-    // TODO: getaddr,
-    // TODO: mempool,
+    /// `getaddr`
+    GetAddr,
+    /// `mempool`
+    MemPool,
     // TODO: checkorder,
     // TODO: submitorder,
     // TODO: reply,
     /// `ping`
     Ping(u64),
     /// `pong`
-    Pong(u64)
-    // TODO: reject,
-    // TODO: bloom filtering
+    Pong(u64),
+    /// `sendheaders`
+    SendHeaders,
+    /// `feefilter`
+    FeeFilter(i64),
+    /// `filterload`
+    FilterLoad(BloomFilter),
+    /// `filteradd`
+    FilterAdd(Vec<u8>),
+    /// `filterclear`
+    FilterClear,
+    /// `merkleblock`
+    MerkleBlock(MerkleBlock),
+    /// `reject`
+    Reject(RejectMessage),
+    /// `sendcmpct`
+    SendCmpct(SendCmpct),
+    /// `cmpctblock`
+    CmpctBlock(HeaderAndShortIds),
+    /// `getblocktxn`
+    GetBlockTxn(GetBlockTxn),
+    /// `blocktxn`
+    BlockTxn(BlockTxn),
+    /// Any other message, kept around verbatim so that messages this
+    /// library does not yet understand can still be relayed or inspected
+    /// rather than causing a hard decode failure.
+    Unknown {
+        /// The command as sent over the wire
+        command: CommandString,
+        /// The message's payload, unparsed
+        payload: Vec<u8>
+    }
     // TODO: alert
 }
 
 impl RawNetworkMessage {
     fn command(&self) -> String {
         match self.payload {
-            NetworkMessage::Version(_) => "version",
-            NetworkMessage::Verack     => "verack",
-            NetworkMessage::Addr(_)    => "addr",
-            NetworkMessage::Inv(_)     => "inv",
-            NetworkMessage::GetData(_) => "getdata",
-            NetworkMessage::NotFound(_) => "notfound",
-            NetworkMessage::GetBlocks(_) => "getblocks",
-            NetworkMessage::GetHeaders(_) => "getheaders",
-            NetworkMessage::Tx(_)      => "tx",
-            NetworkMessage::Block(_)   => "block",
-            NetworkMessage::Headers(_) => "headers",
-            NetworkMessage::Ping(_)    => "ping",
-            NetworkMessage::Pong(_)    => "pong",
-        }.to_owned()
+            NetworkMessage::Version(_) => "version".to_owned(),
+            NetworkMessage::Verack     => "verack".to_owned(),
+            NetworkMessage::Addr(_)    => "addr".to_owned(),
+            NetworkMessage::Inv(_)     => "inv".to_owned(),
+            NetworkMessage::GetData(_) => "getdata".to_owned(),
+            NetworkMessage::NotFound(_) => "notfound".to_owned(),
+            NetworkMessage::GetBlocks(_) => "getblocks".to_owned(),
+            NetworkMessage::GetHeaders(_) => "getheaders".to_owned(),
+            NetworkMessage::Tx(_)      => "tx".to_owned(),
+            NetworkMessage::Block(_)   => "block".to_owned(),
+            NetworkMessage::Headers(_) => "headers".to_owned(),
+            NetworkMessage::GetAddr    => "getaddr".to_owned(),
+            NetworkMessage::MemPool    => "mempool".to_owned(),
+            NetworkMessage::Ping(_)    => "ping".to_owned(),
+            NetworkMessage::Pong(_)    => "pong".to_owned(),
+            NetworkMessage::SendHeaders => "sendheaders".to_owned(),
+            NetworkMessage::FeeFilter(_) => "feefilter".to_owned(),
+            NetworkMessage::FilterLoad(_) => "filterload".to_owned(),
+            NetworkMessage::FilterAdd(_) => "filteradd".to_owned(),
+            NetworkMessage::FilterClear => "filterclear".to_owned(),
+            NetworkMessage::MerkleBlock(_) => "merkleblock".to_owned(),
+            NetworkMessage::Reject(_)     => "reject".to_owned(),
+            NetworkMessage::SendCmpct(_)  => "sendcmpct".to_owned(),
+            NetworkMessage::CmpctBlock(_) => "cmpctblock".to_owned(),
+            NetworkMessage::GetBlockTxn(_) => "getblocktxn".to_owned(),
+            NetworkMessage::BlockTxn(_)   => "blocktxn".to_owned(),
+            NetworkMessage::Unknown { command: CommandString(ref cmd), .. } => cmd.clone(),
+        }
     }
 }
 
@@ -153,8 +801,22 @@ impl<S: SimpleEncoder> ConsensusEncodable<S> for RawNetworkMessage {
             NetworkMessage::Tx(ref dat)      => serialize(dat),
             NetworkMessage::Block(ref dat)   => serialize(dat),
             NetworkMessage::Headers(ref dat) => serialize(dat),
+            NetworkMessage::GetAddr          => Ok(vec![]),
+            NetworkMessage::MemPool          => Ok(vec![]),
             NetworkMessage::Ping(ref dat)    => serialize(dat),
             NetworkMessage::Pong(ref dat)    => serialize(dat),
+            NetworkMessage::SendHeaders      => Ok(vec![]),
+            NetworkMessage::FeeFilter(ref dat) => serialize(dat),
+            NetworkMessage::FilterLoad(ref dat) => serialize(dat),
+            NetworkMessage::FilterAdd(ref dat) => serialize(dat),
+            NetworkMessage::FilterClear       => Ok(vec![]),
+            NetworkMessage::MerkleBlock(ref dat) => serialize(dat),
+            NetworkMessage::Reject(ref dat)   => serialize(dat),
+            NetworkMessage::SendCmpct(ref dat) => serialize(dat),
+            NetworkMessage::CmpctBlock(ref dat) => serialize(dat),
+            NetworkMessage::GetBlockTxn(ref dat) => serialize(dat),
+            NetworkMessage::BlockTxn(ref dat) => serialize(dat),
+            NetworkMessage::Unknown { payload: ref dat, .. } => Ok(dat.clone()),
         }.unwrap()).consensus_encode(s));
         Ok(())
     }
@@ -168,7 +830,7 @@ impl<D: SimpleDecoder<Error=util::Error>> ConsensusDecodable<D> for RawNetworkMe
         let CommandString(cmd): CommandString= try!(ConsensusDecodable::consensus_decode(d));
         let CheckedData(raw_payload): CheckedData = try!(ConsensusDecodable::consensus_decode(d));
 
-        let mut mem_d = RawDecoder::new(Cursor::new(raw_payload));
+        let mut mem_d = RawDecoder::new(Cursor::new(raw_payload.clone()));
         let payload = match &cmd[..] {
             "version" => NetworkMessage::Version(try!(propagate_err("version".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
             "verack"  => NetworkMessage::Verack,
@@ -180,10 +842,23 @@ impl<D: SimpleDecoder<Error=util::Error>> ConsensusDecodable<D> for RawNetworkMe
             "getheaders" => NetworkMessage::GetHeaders(try!(propagate_err("getheaders".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
             "block"   => NetworkMessage::Block(try!(propagate_err("block".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
             "headers" => NetworkMessage::Headers(try!(propagate_err("headers".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "getaddr" => NetworkMessage::GetAddr,
+            "mempool" => NetworkMessage::MemPool,
             "ping"    => NetworkMessage::Ping(try!(propagate_err("ping".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
-            "pong"    => NetworkMessage::Ping(try!(propagate_err("pong".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "pong"    => NetworkMessage::Pong(try!(propagate_err("pong".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "sendheaders" => NetworkMessage::SendHeaders,
+            "feefilter" => NetworkMessage::FeeFilter(try!(propagate_err("feefilter".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
             "tx"      => NetworkMessage::Tx(try!(propagate_err("tx".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
-            cmd => return Err(d.error(format!("unrecognized network command `{}`", cmd)))
+            "filterload" => NetworkMessage::FilterLoad(try!(propagate_err("filterload".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "filteradd" => NetworkMessage::FilterAdd(try!(propagate_err("filteradd".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "filterclear" => NetworkMessage::FilterClear,
+            "merkleblock" => NetworkMessage::MerkleBlock(try!(propagate_err("merkleblock".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "reject"  => NetworkMessage::Reject(try!(propagate_err("reject".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "sendcmpct" => NetworkMessage::SendCmpct(try!(propagate_err("sendcmpct".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "cmpctblock" => NetworkMessage::CmpctBlock(try!(propagate_err("cmpctblock".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "getblocktxn" => NetworkMessage::GetBlockTxn(try!(propagate_err("getblocktxn".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            "blocktxn" => NetworkMessage::BlockTxn(try!(propagate_err("blocktxn".to_owned(), ConsensusDecodable::consensus_decode(&mut mem_d)))),
+            unknown   => NetworkMessage::Unknown { command: CommandString(unknown.to_owned()), payload: raw_payload },
         };
         Ok(RawNetworkMessage {
             magic: magic,
@@ -194,9 +869,142 @@ impl<D: SimpleDecoder<Error=util::Error>> ConsensusDecodable<D> for RawNetworkMe
 
 #[cfg(test)]
 mod test {
-    use super::{RawNetworkMessage, NetworkMessage, CommandString};
+    use super::{RawNetworkMessage, NetworkMessage, CommandString, BloomFilter, HeaderAndShortIds, MerkleBlock,
+                RejectMessage, RejectReason, ShortIdKeys,
+                encode_differential_indexes, decode_differential_indexes,
+                short_id_to_bytes, short_id_from_bytes};
 
+    use blockdata::block;
+    use blockdata::script::Script;
+    use blockdata::transaction::{Transaction, TxIn, TxOut};
     use network::serialize::{deserialize, serialize};
+    use util::hash::Sha256dHash;
+
+    #[test]
+    fn bloom_filter_insert_contains_test() {
+        let mut filter = BloomFilter { filter: vec![0u8; 8], n_hash_funcs: 3, n_tweak: 0, n_flags: 0 };
+        filter.insert(b"hello");
+        assert!(filter.contains(b"hello"));
+        assert!(!filter.contains(b"goodbye"));
+    }
+
+    #[test]
+    fn bloom_filter_empty_does_not_panic_test() {
+        let mut filter = BloomFilter { filter: vec![], n_hash_funcs: 3, n_tweak: 0, n_flags: 0 };
+        filter.insert(b"hello");
+        assert!(!filter.contains(b"hello"));
+    }
+
+    #[test]
+    fn merkleblock_build_extract_test() {
+        // Two leaves, the first one matched. Flag bits, LSB first: expand the
+        // root (1), leaf 0 matches (1), leaf 1 does not (0).
+        let leaf0 = Sha256dHash::from_data(b"tx0");
+        let leaf1 = Sha256dHash::from_data(b"tx1");
+        let mut root_preimage = Vec::new();
+        root_preimage.extend_from_slice(&leaf0.data()[..]);
+        root_preimage.extend_from_slice(&leaf1.data()[..]);
+        let root = Sha256dHash::from_data(&root_preimage);
+
+        let header = block::BlockHeader {
+            version: 1,
+            prev_blockhash: Sha256dHash::from_data(b""),
+            merkle_root: root,
+            time: 0,
+            bits: 0,
+            nonce: 0
+        };
+        let merkleblock = MerkleBlock {
+            header: header,
+            total_transactions: 2,
+            hashes: vec![leaf0, leaf1],
+            flags: vec![0x03]
+        };
+        assert_eq!(merkleblock.extract_matches(), Some(vec![leaf0]));
+    }
+
+    #[test]
+    fn merkleblock_rejects_leftover_hashes_and_bits_test() {
+        let leaf0 = Sha256dHash::from_data(b"tx0");
+        let leaf1 = Sha256dHash::from_data(b"tx1");
+        let mut root_preimage = Vec::new();
+        root_preimage.extend_from_slice(&leaf0.data()[..]);
+        root_preimage.extend_from_slice(&leaf1.data()[..]);
+        let root = Sha256dHash::from_data(&root_preimage);
+        let header = block::BlockHeader {
+            version: 1,
+            prev_blockhash: Sha256dHash::from_data(b""),
+            merkle_root: root,
+            time: 0,
+            bits: 0,
+            nonce: 0
+        };
+
+        // An extra, unconsumed hash appended after a valid tree.
+        let extra_hash = MerkleBlock {
+            header: header.clone(),
+            total_transactions: 2,
+            hashes: vec![leaf0, leaf1, Sha256dHash::from_data(b"unused")],
+            flags: vec![0x03]
+        };
+        assert_eq!(extra_hash.extract_matches(), None);
+
+        // A non-zero padding bit after the bits the walk actually consumed.
+        let extra_bit = MerkleBlock {
+            header: header,
+            total_transactions: 2,
+            hashes: vec![leaf0, leaf1],
+            flags: vec![0x03 | 0x08]
+        };
+        assert_eq!(extra_bit.extract_matches(), None);
+    }
+
+    fn dummy_tx(lock_time: u32) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: lock_time,
+            input: vec![TxIn {
+                prev_hash: Sha256dHash::from_data(b""),
+                prev_index: 0,
+                script_sig: Script(vec![]),
+                sequence: 0xffffffff
+            }],
+            output: vec![TxOut { value: 50_00000000, script_pubkey: Script(vec![]) }]
+        }
+    }
+
+    #[test]
+    fn cmpctblock_reconstruct_test() {
+        let coinbase = dummy_tx(0);
+        let tx1 = dummy_tx(1);
+
+        let header = block::BlockHeader {
+            version: 1,
+            prev_blockhash: Sha256dHash::from_data(b""),
+            merkle_root: Sha256dHash::from_data(b""),
+            time: 0,
+            bits: 0,
+            nonce: 0
+        };
+        let nonce = 42;
+        let short_id = ShortIdKeys::new(&header, nonce).unwrap().short_id(&tx1.bitcoin_hash());
+        let short_ids = HeaderAndShortIds {
+            header: header,
+            nonce: nonce,
+            short_ids: vec![short_id],
+            prefilled: vec![(0, coinbase.clone())]
+        };
+
+        // All transactions known: everything is reconstructed, nothing missing.
+        let (reconstructed, missing) = short_ids.reconstruct(&[tx1.clone()]).unwrap();
+        assert_eq!(reconstructed, vec![Some(coinbase.clone()), Some(tx1.clone())]);
+        assert!(missing.is_empty());
+
+        // tx1 unknown: its slot is reported missing instead of reconstructed.
+        let (reconstructed, missing) = short_ids.reconstruct(&[]).unwrap();
+        assert_eq!(reconstructed, vec![Some(coinbase), None]);
+        assert_eq!(missing, vec![1]);
+    }
 
     #[test]
     fn serialize_commandstring_test() {
@@ -212,6 +1020,19 @@ mod test {
 
         let short_cs: Result<CommandString, _> = deserialize(&[0x41u8, 0x6e, 0x64, 0x72, 0x65, 0x77, 0, 0, 0, 0, 0]);
         assert!(short_cs.is_err());
+
+        // a non-zero byte following a NUL terminator is not a valid command
+        let garbage_after_nul: Result<CommandString, _> =
+            deserialize(&[0x41u8, 0x6e, 0, 0x72, 0x65, 0x77, 0, 0, 0, 0, 0, 0]);
+        assert!(garbage_after_nul.is_err());
+    }
+
+    #[test]
+    fn commandstring_try_from_test() {
+        assert!(CommandString::try_from("verack").is_ok());
+        assert!(CommandString::try_from("123456789012").is_ok());
+        assert!(CommandString::try_from("1234567890123").is_err());
+        assert!(CommandString::try_from("bad\0cmd").is_err());
     }
 
     #[test]
@@ -222,6 +1043,77 @@ mod test {
                                        0x00, 0x00, 0x00, 0x00, 0x5d, 0xf6, 0xe0, 0xe2]));
     }
 
+    #[test]
+    fn serialize_deserialize_unknown_test() {
+        let msg = RawNetworkMessage {
+            magic: 0xd9b4bef9,
+            payload: NetworkMessage::Unknown {
+                command: CommandString("addrv2".to_owned()),
+                payload: vec![1, 2, 3, 4]
+            }
+        };
+        let serialized = serialize(&msg).unwrap();
+        let deserialized: RawNetworkMessage = deserialize(&serialized).unwrap();
+        match deserialized.payload {
+            NetworkMessage::Unknown { command, payload } => {
+                assert_eq!(command, CommandString("addrv2".to_owned()));
+                assert_eq!(payload, vec![1, 2, 3, 4]);
+            }
+            _ => panic!("expected an Unknown message")
+        }
+    }
+
+    #[test]
+    fn serialize_deserialize_reject_test() {
+        let msg = RawNetworkMessage {
+            magic: 0xd9b4bef9,
+            payload: NetworkMessage::Reject(RejectMessage {
+                message: CommandString("tx".to_owned()),
+                ccode: RejectReason::InsufficientFee,
+                reason: "insufficient priority".to_owned(),
+                hash: None
+            })
+        };
+        let serialized = serialize(&msg).unwrap();
+        let deserialized: RawNetworkMessage = deserialize(&serialized).unwrap();
+        match deserialized.payload {
+            NetworkMessage::Reject(rejmsg) => {
+                assert_eq!(rejmsg.message, CommandString("tx".to_owned()));
+                assert_eq!(rejmsg.ccode, RejectReason::InsufficientFee);
+                assert_eq!(rejmsg.reason, "insufficient priority".to_owned());
+                assert_eq!(rejmsg.hash, None);
+            }
+            _ => panic!("expected a Reject message")
+        }
+    }
+
+    #[test]
+    fn serialize_deserialize_control_messages_test() {
+        for payload in vec![NetworkMessage::GetAddr, NetworkMessage::MemPool, NetworkMessage::SendHeaders,
+                             NetworkMessage::FeeFilter(1000)] {
+            let msg = RawNetworkMessage { magic: 0xd9b4bef9, payload: payload.clone() };
+            let serialized = serialize(&msg).unwrap();
+            let deserialized: RawNetworkMessage = deserialize(&serialized).unwrap();
+            assert_eq!(deserialized.payload, payload);
+        }
+    }
+
+    #[test]
+    fn short_id_wire_format_test() {
+        // a short id only ever occupies 6 bytes on the wire, never 8
+        let id = 0x0011223344556677u64 & 0x0000ffffffffffff;
+        assert_eq!(short_id_to_bytes(id), [0x77, 0x66, 0x55, 0x44, 0x33, 0x22]);
+        assert_eq!(short_id_from_bytes(&short_id_to_bytes(id)), id);
+    }
+
+    #[test]
+    fn differential_index_roundtrip_test() {
+        let indexes = vec![2u64, 5, 6, 10];
+        let encoded = encode_differential_indexes(&indexes);
+        assert_eq!(encoded, vec![2, 2, 0, 3]);
+        assert_eq!(decode_differential_indexes(&encoded), indexes);
+    }
+
     #[test]
     fn serialize_ping_test() {
         assert_eq!(serialize(&RawNetworkMessage { magic: 0xd9b4bef9, payload: NetworkMessage::Ping(100) }).ok(),
@@ -230,5 +1122,16 @@ mod test {
                                        0x08, 0x00, 0x00, 0x00, 0x24, 0x67, 0xf1, 0x1d,
                                        0x64, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]));
     }
+
+    #[test]
+    fn serialize_deserialize_pong_test() {
+        let msg = RawNetworkMessage { magic: 0xd9b4bef9, payload: NetworkMessage::Pong(100) };
+        let serialized = serialize(&msg).unwrap();
+        let deserialized: RawNetworkMessage = deserialize(&serialized).unwrap();
+        match deserialized.payload {
+            NetworkMessage::Pong(nonce) => assert_eq!(nonce, 100),
+            _ => panic!("expected a Pong message")
+        }
+    }
 }
 
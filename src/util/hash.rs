@@ -0,0 +1,152 @@
+// Rust Bitcoin Library
+// Written in 2014 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Hash functions
+//!
+//! Hash functions used elsewhere in the library that are not already
+//! exposed through `Sha256dHash`.
+//!
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+
+/// 32-bit MurmurHash3 (x86 variant), as used by BIP37 bloom filters
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+
+    let mut h1 = seed;
+
+    let tail_len = data.len() % 4;
+    let tail = &data[data.len() - tail_len..];
+    for chunk in data[..data.len() - tail_len].chunks(4) {
+        let mut k1 = (chunk[0] as u32) | (chunk[1] as u32) << 8 |
+                     (chunk[2] as u32) << 16 | (chunk[3] as u32) << 24;
+        k1 = k1.wrapping_mul(C1);
+        k1 = k1.rotate_left(15);
+        k1 = k1.wrapping_mul(C2);
+        h1 ^= k1;
+        h1 = h1.rotate_left(13);
+        h1 = h1.wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+
+    let mut k1 = 0u32;
+    for (i, &byte) in tail.iter().enumerate() {
+        k1 ^= (byte as u32) << (8 * i);
+    }
+    if !tail.is_empty() {
+        k1 = k1.wrapping_mul(C1);
+        k1 = k1.rotate_left(15);
+        k1 = k1.wrapping_mul(C2);
+        h1 ^= k1;
+    }
+
+    h1 ^= data.len() as u32;
+    h1 ^= h1 >> 16;
+    h1 = h1.wrapping_mul(0x85ebca6b);
+    h1 ^= h1 >> 13;
+    h1 = h1.wrapping_mul(0xc2b2ae35);
+    h1 ^= h1 >> 16;
+    h1
+}
+
+/// Single-round (not double) SHA-256, used to derive the short-id keys for
+/// BIP152 compact blocks
+pub fn single_sha256(data: &[u8]) -> [u8; 32] {
+    let mut engine = Sha256::new();
+    engine.input(data);
+    let mut ret = [0u8; 32];
+    engine.result(&mut ret);
+    ret
+}
+
+/// SipHash-2-4, keyed with (k0, k1), as used to compute BIP152 short ids
+pub fn siphash24(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    macro_rules! sipround {
+        ($v0:expr, $v1:expr, $v2:expr, $v3:expr) => {{
+            $v0 = $v0.wrapping_add($v1); $v1 = $v1.rotate_left(13); $v1 ^= $v0; $v0 = $v0.rotate_left(32);
+            $v2 = $v2.wrapping_add($v3); $v3 = $v3.rotate_left(16); $v3 ^= $v2;
+            $v0 = $v0.wrapping_add($v3); $v3 = $v3.rotate_left(21); $v3 ^= $v0;
+            $v2 = $v2.wrapping_add($v1); $v1 = $v1.rotate_left(17); $v1 ^= $v2; $v2 = $v2.rotate_left(32);
+        }}
+    }
+
+    let mut v0 = 0x736f6d6570736575u64 ^ k0;
+    let mut v1 = 0x646f72616e646f6du64 ^ k1;
+    let mut v2 = 0x6c7967656e657261u64 ^ k0;
+    let mut v3 = 0x7465646279746573u64 ^ k1;
+
+    let nblocks = data.len() / 8;
+    for i in 0..nblocks {
+        let mut m = 0u64;
+        for j in 0..8 {
+            m |= (data[i*8+j] as u64) << (8*j);
+        }
+        v3 ^= m;
+        sipround!(v0, v1, v2, v3);
+        sipround!(v0, v1, v2, v3);
+        v0 ^= m;
+    }
+
+    let tail = &data[nblocks*8..];
+    let mut t = (data.len() as u64) << 56;
+    for (i, &byte) in tail.iter().enumerate() {
+        t |= (byte as u64) << (8 * i);
+    }
+
+    v3 ^= t;
+    sipround!(v0, v1, v2, v3);
+    sipround!(v0, v1, v2, v3);
+    v0 ^= t;
+    v2 ^= 0xff;
+    sipround!(v0, v1, v2, v3);
+    sipround!(v0, v1, v2, v3);
+    sipround!(v0, v1, v2, v3);
+    sipround!(v0, v1, v2, v3);
+
+    v0 ^ v1 ^ v2 ^ v3
+}
+
+#[cfg(test)]
+mod test {
+    use super::{murmur3_32, single_sha256, siphash24};
+
+    #[test]
+    fn murmur3_32_test_vectors() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"", 1), 0x514e28b7);
+        assert_eq!(murmur3_32(b"test", 0), 0xba6bd213);
+    }
+
+    #[test]
+    fn single_sha256_test_vectors() {
+        assert_eq!(single_sha256(b""),
+                   [0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14,
+                    0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9, 0x24,
+                    0x27, 0xae, 0x41, 0xe4, 0x64, 0x9b, 0x93, 0x4c,
+                    0xa4, 0x95, 0x99, 0x1b, 0x78, 0x52, 0xb8, 0x55]);
+        assert_eq!(single_sha256(b"abc"),
+                   [0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea,
+                    0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22, 0x23,
+                    0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c,
+                    0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00, 0x15, 0xad]);
+    }
+
+    #[test]
+    fn siphash24_test_vector() {
+        // From the reference SipHash implementation, with test keys
+        // k0 = 0x0706050403020100, k1 = 0x0f0e0d0c0b0a0908 and an empty message
+        assert_eq!(siphash24(0x0706050403020100, 0x0f0e0d0c0b0a0908, &[]), 0x726fdb47dd0e0e31);
+    }
+}